@@ -2,13 +2,15 @@
 //!
 //! This crate is only works on `unix` targets and is `no_std` compatible.
 #![cfg(unix)]
-#![no_std]
+#![cfg_attr(not(feature = "std"), no_std)]
 // Inspired by https://github.com/lassik/shm_open_anon (ISC license, Copyright 2019 Lassi Kortela)
 
+#[cfg(feature = "std")]
+extern crate std;
+
 use libc::c_int;
 
-#[cfg(not(any(target_os = "freebsd", target_os = "android")))]
-fn errno() -> c_int {
+fn errno_location() -> *mut c_int {
     #[cfg(any(target_os = "solaris", target_os = "illumos"))]
     use libc::___errno as errno_location;
     #[cfg(any(target_os = "android", target_os = "netbsd", target_os = "openbsd"))]
@@ -18,13 +20,30 @@ fn errno() -> c_int {
     #[cfg(any(target_os = "freebsd", target_os = "ios", target_os = "macos"))]
     use libc::__error as errno_location;
 
-    unsafe { *errno_location() as c_int }
+    // Safety: each of these is a libc-provided function returning the address of `errno` for
+    // the current thread.
+    unsafe { errno_location() }
+}
+
+fn errno() -> c_int {
+    unsafe { *errno_location() }
+}
+
+/// Sets `errno` to `value`, for reporting failure from paths that have no underlying libc call
+/// of their own to set it (e.g. an unsupported operation on a given backend).
+fn set_errno(value: c_int) {
+    unsafe { *errno_location() = value };
 }
 
 #[cfg(not(any(target_os = "freebsd", target_os = "android")))]
-fn shm_open_anonymous_posix() -> c_int {
+fn shm_open_anonymous_posix(inheritable: bool) -> c_int {
     use libc::c_char;
 
+    let mut open_flags = libc::O_RDWR | libc::O_CREAT | libc::O_EXCL | libc::O_NOFOLLOW;
+    if !inheritable {
+        open_flags |= libc::O_CLOEXEC;
+    }
+
     let mut filename = *b"/shm_open_anonymous-XXXX\0";
     const OFFSET: usize = 20;
     assert_eq!(&filename[OFFSET..], b"XXXX\0");
@@ -38,13 +57,7 @@ fn shm_open_anonymous_posix() -> c_int {
         // If creation fails with EEXIST, try another filename until it works.
 
         // Safety: path points to a null-terminated string
-        let fd = unsafe {
-            libc::shm_open(
-                path,
-                libc::O_RDWR | libc::O_CREAT | libc::O_EXCL | libc::O_NOFOLLOW,
-                0o600,
-            )
-        };
+        let fd = unsafe { libc::shm_open(path, open_flags, 0o600) };
         if fd == -1 && errno() != libc::EEXIST {
             return -1;
         } else if fd != -1 {
@@ -78,28 +91,87 @@ fn shm_open_anonymous_posix() -> c_int {
     }
 }
 
+// Android's libc (bionic) doesn't declare `shm_open`/`shm_unlink`, so there is no generic POSIX
+// fallback available there when `memfd_create` is missing; report that plainly instead of
+// falling through to a nonexistent symbol.
+#[cfg(target_os = "android")]
+fn shm_open_anonymous_posix(inheritable: bool) -> c_int {
+    let _ = inheritable;
+    set_errno(libc::ENOSYS);
+    -1
+}
+
+// Resolves `memfd_create` as a weak dynamic symbol rather than calling it through
+// `libc::syscall(SYS_memfd_create, ...)`: `SYS_memfd_create` isn't defined for every
+// architecture libc targets, and going through libc's own wrapper (when present) is preferred
+// over a raw syscall. When the symbol is missing (e.g. an older kernel/libc), the caller falls
+// back to the generic POSIX path, the same way std resolves optional libc entry points.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod weak {
+    use libc::{c_char, c_int, c_uint};
+
+    // `memfd_create`'s flags parameter is `unsigned int`, matching libc's own declaration and
+    // the `MFD_*` constants, which are all `c_uint`.
+    type MemfdCreate = unsafe extern "C" fn(*const c_char, c_uint) -> c_int;
+
+    // 1 is never a valid function address, so it doubles as the "not yet resolved" sentinel.
+    const UNINIT: usize = 1;
+    static MEMFD_CREATE: core::sync::atomic::AtomicUsize =
+        core::sync::atomic::AtomicUsize::new(UNINIT);
+
+    pub(super) fn memfd_create() -> Option<MemfdCreate> {
+        use core::sync::atomic::Ordering;
+
+        let addr = match MEMFD_CREATE.load(Ordering::Relaxed) {
+            UNINIT => {
+                // Safety: the symbol name is a valid, null-terminated C string.
+                let resolved =
+                    unsafe { libc::dlsym(libc::RTLD_DEFAULT, c"memfd_create".as_ptr()) } as usize;
+                MEMFD_CREATE.store(resolved, Ordering::Relaxed);
+                resolved
+            }
+            resolved => resolved,
+        };
+
+        if addr == 0 {
+            None
+        } else {
+            // Safety: addr was resolved by dlsym for the `memfd_create` symbol, which has the
+            // signature of `MemfdCreate`.
+            Some(unsafe { core::mem::transmute::<usize, MemfdCreate>(addr) })
+        }
+    }
+}
+
 #[cfg(any(target_os = "linux", target_os = "android"))]
-fn memfd_create() -> c_int {
+fn memfd_create_with(flags: libc::c_uint) -> c_int {
     static PATH: &'static str = "shm_open_anonymous\0";
-    // PATH is a valid string
-    let fd = unsafe {
-        libc::syscall(
-            libc::SYS_memfd_create,
-            PATH.as_ptr() as *const libc::c_char,
-            libc::MFD_CLOEXEC,
-        )
-    };
-    fd as c_int
+
+    match weak::memfd_create() {
+        // Safety: PATH is a valid, null-terminated string.
+        Some(memfd_create) => unsafe { memfd_create(PATH.as_ptr() as *const libc::c_char, flags) },
+        None => {
+            set_errno(libc::ENOSYS);
+            -1
+        }
+    }
 }
 
-#[cfg(target_os = "linux")]
-fn memfd_create_fallback_posix() -> c_int {
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn memfd_create(inheritable: bool) -> c_int {
+    let flags = if inheritable { 0 } else { libc::MFD_CLOEXEC };
+    memfd_create_with(flags)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn memfd_create_fallback_posix(inheritable: bool) -> c_int {
     // Try opening with memfd_create.
-    // If that fails (because of an older kernel) use the generic POSIX method.
-    let fd = memfd_create();
+    // If that fails (because the symbol or kernel support is missing) use the generic POSIX
+    // method instead.
+    let fd = memfd_create(inheritable);
     if fd == -1 {
         if errno() == libc::ENOSYS {
-            shm_open_anonymous_posix()
+            shm_open_anonymous_posix(inheritable)
         } else {
             -1
         }
@@ -109,9 +181,13 @@ fn memfd_create_fallback_posix() -> c_int {
 }
 
 #[cfg(target_os = "freebsd")]
-fn shm_open_shm_anon() -> c_int {
-    // no invariants to uphold
-    unsafe { libc::shm_open(libc::SHM_ANON, libc::O_RDWR, 0) }
+fn shm_open_shm_anon(inheritable: bool) -> c_int {
+    let mut open_flags = libc::O_RDWR;
+    if !inheritable {
+        open_flags |= libc::O_CLOEXEC;
+    }
+    // no other invariants to uphold
+    unsafe { libc::shm_open(libc::SHM_ANON, open_flags, 0) }
 }
 
 /// Creates an anonymous POSIX shared memory object.
@@ -123,18 +199,265 @@ fn shm_open_shm_anon() -> c_int {
 ///
 /// Depending on operating system, this function may use an OS-specific system call for creating
 /// the memory object, or it may use a generic POSIX implementation.
+///
+/// The returned file descriptor is closed on `exec`. To get a descriptor that survives `exec`,
+/// use [`shm_open_anonymous_with`].
 pub fn shm_open_anonymous() -> c_int {
-    #[cfg(target_os = "linux")]
-    return memfd_create_fallback_posix();
+    shm_open_anonymous_with(false)
+}
 
-    #[cfg(target_os = "android")]
-    return memfd_create();
+/// Creates an anonymous POSIX shared memory object, like [`shm_open_anonymous`], but lets the
+/// caller control whether the returned file descriptor is inherited across `exec`.
+///
+/// If `inheritable` is `false`, the descriptor is created close-on-exec, as if by
+/// `MFD_CLOEXEC`/`O_CLOEXEC` (this is what [`shm_open_anonymous`] does). If `inheritable` is
+/// `true`, the descriptor survives `exec`. This is consistent across every backend this crate
+/// supports.
+///
+/// On failure, returns -1 and sets `errno`.
+pub fn shm_open_anonymous_with(inheritable: bool) -> c_int {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    return memfd_create_fallback_posix(inheritable);
 
     #[cfg(target_os = "freebsd")]
-    return shm_open_shm_anon();
+    return shm_open_shm_anon(inheritable);
 
     #[cfg(not(any(target_os = "linux", target_os = "android", target_os = "freebsd")))]
-    return shm_open_anonymous_posix();
+    return shm_open_anonymous_posix(inheritable);
+}
+
+/// A set of memfd seals, as applied by [`add_seals`] and read back by [`get_seals`].
+///
+/// See `fcntl(2)`'s description of `F_ADD_SEALS`/`F_GET_SEALS` for the exact semantics of each
+/// seal. Seals are combined with `|`, e.g. `Seals::SHRINK | Seals::GROW`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Seals(c_int);
+
+impl Seals {
+    /// No seals.
+    pub const NONE: Seals = Seals(0);
+    /// Prevents any further seals (including this one) from being added.
+    pub const SEAL: Seals = Seals(libc::F_SEAL_SEAL);
+    /// Prevents the file from being reduced in size.
+    pub const SHRINK: Seals = Seals(libc::F_SEAL_SHRINK);
+    /// Prevents the file from being increased in size.
+    pub const GROW: Seals = Seals(libc::F_SEAL_GROW);
+    /// Prevents writes and writable mappings once no writable mapping remains. Fails with
+    /// `EBUSY` if a writable mapping is still open when this seal is added.
+    pub const WRITE: Seals = Seals(libc::F_SEAL_WRITE);
+    /// Prevents new writable mappings from being created, without disturbing existing ones.
+    pub const FUTURE_WRITE: Seals = Seals(libc::F_SEAL_FUTURE_WRITE);
+
+    /// Returns whether `self` has every seal set in `other`.
+    pub fn contains(self, other: Seals) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl core::ops::BitOr for Seals {
+    type Output = Seals;
+    fn bitor(self, rhs: Seals) -> Seals {
+        Seals(self.0 | rhs.0)
+    }
+}
+
+impl core::ops::BitOrAssign for Seals {
+    fn bitor_assign(&mut self, rhs: Seals) {
+        self.0 |= rhs.0;
+    }
+}
+
+/// Creates an anonymous POSIX shared memory object that supports having seals applied to it
+/// with [`add_seals`].
+///
+/// This behaves like [`shm_open_anonymous`], except that on Linux/Android the underlying memfd
+/// is created with `MFD_ALLOW_SEALING`, which is required before any seal may be added. Unlike
+/// [`shm_open_anonymous`], there is no POSIX fallback: if `memfd_create` is unavailable, this
+/// fails, returning -1 with `errno` set to `ENOSYS`.
+///
+/// On backends that cannot create a sealable object (every target other than Linux and Android),
+/// this always fails, returning -1 with `errno` set to `ENOSYS`.
+pub fn shm_open_anonymous_sealable() -> c_int {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    return memfd_create_with(libc::MFD_CLOEXEC | libc::MFD_ALLOW_SEALING);
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    {
+        set_errno(libc::ENOSYS);
+        -1
+    }
+}
+
+/// Adds `seals` to the set of seals already applied to `fd`, which must have been created by
+/// [`shm_open_anonymous_sealable`].
+///
+/// On success, returns `0`; use [`get_seals`] to read back the resulting combined set. On
+/// failure, returns -1 with `errno` set. Notably, this fails with `EBUSY` if [`Seals::WRITE`] is
+/// requested while a writable mapping of `fd` still exists, and fails with `EPERM` if
+/// [`Seals::SEAL`] was already applied.
+///
+/// On backends without memfd support, this always fails with `errno` set to `ENOSYS`.
+///
+/// # Safety
+///
+/// `fd` must be a valid, open file descriptor.
+pub unsafe fn add_seals(fd: c_int, seals: Seals) -> c_int {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    return libc::fcntl(fd, libc::F_ADD_SEALS, seals.0);
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    {
+        let _ = (fd, seals);
+        set_errno(libc::ENOSYS);
+        -1
+    }
+}
+
+/// Reads back the set of seals currently applied to `fd`, which must have been created by
+/// [`shm_open_anonymous_sealable`].
+///
+/// On backends without memfd support, this always fails, returning `None` with `errno` set to
+/// `ENOSYS`.
+///
+/// # Safety
+///
+/// `fd` must be a valid, open file descriptor.
+pub unsafe fn get_seals(fd: c_int) -> Option<Seals> {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        let seals = libc::fcntl(fd, libc::F_GET_SEALS);
+        if seals == -1 {
+            None
+        } else {
+            Some(Seals(seals))
+        }
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    {
+        let _ = fd;
+        set_errno(libc::ENOSYS);
+        None
+    }
+}
+
+/// A requested huge page size for [`SizedOptions::huge_page`].
+///
+/// Only honored on Linux, where it selects one of the hugetlbfs page sizes `memfd_create`
+/// accepts alongside `MFD_HUGETLB`. On every other target, requesting a `HugePageSize` makes
+/// [`shm_open_anonymous_sized_with`] fail with `errno` set to `ENOSYS`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HugePageSize {
+    /// 2 MB huge pages.
+    Mb2,
+    /// 1 GB huge pages.
+    Gb1,
+}
+
+#[cfg(target_os = "linux")]
+const MFD_HUGE_SHIFT: libc::c_uint = 26;
+#[cfg(target_os = "linux")]
+const MFD_HUGE_2MB: libc::c_uint = 21 << MFD_HUGE_SHIFT;
+#[cfg(target_os = "linux")]
+const MFD_HUGE_1GB: libc::c_uint = 30 << MFD_HUGE_SHIFT;
+
+/// Options for [`shm_open_anonymous_sized_with`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SizedOptions {
+    huge_page: Option<HugePageSize>,
+}
+
+impl SizedOptions {
+    /// Creates the default options: no huge page backing requested.
+    pub fn new() -> SizedOptions {
+        SizedOptions::default()
+    }
+
+    /// Requests that the object be backed by huge pages of the given size.
+    ///
+    /// Only supported on Linux; [`shm_open_anonymous_sized_with`] reports `ENOSYS` if this is
+    /// set on any other target.
+    pub fn huge_page(mut self, size: HugePageSize) -> SizedOptions {
+        self.huge_page = Some(size);
+        self
+    }
+}
+
+fn shm_open_anonymous_for(options: SizedOptions) -> c_int {
+    if let Some(size) = options.huge_page {
+        #[cfg(target_os = "linux")]
+        {
+            let huge_flag = match size {
+                HugePageSize::Mb2 => MFD_HUGE_2MB,
+                HugePageSize::Gb1 => MFD_HUGE_1GB,
+            };
+            return memfd_create_with(libc::MFD_CLOEXEC | libc::MFD_HUGETLB | huge_flag);
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = size;
+            set_errno(libc::ENOSYS);
+            return -1;
+        }
+    }
+
+    shm_open_anonymous()
+}
+
+/// Creates an anonymous POSIX shared memory object and sets its length to `len`, as if by
+/// `shm_open_anonymous` followed by `ftruncate`.
+///
+/// On failure, returns -1 and sets `errno`; this covers both object creation and the subsequent
+/// `ftruncate`, so any partially created object is closed before returning.
+pub fn shm_open_anonymous_sized(len: usize) -> c_int {
+    shm_open_anonymous_sized_with(len, SizedOptions::new())
+}
+
+/// Like [`shm_open_anonymous_sized`], but with additional creation `options`, such as requesting
+/// huge page backing via [`SizedOptions::huge_page`].
+///
+/// On targets that cannot honor a requested option (e.g. huge pages outside of Linux), this
+/// fails, returning -1 with `errno` set to `ENOSYS`.
+pub fn shm_open_anonymous_sized_with(len: usize, options: SizedOptions) -> c_int {
+    let fd = shm_open_anonymous_for(options);
+    if fd == -1 {
+        return -1;
+    }
+
+    // Safety: fd was just created above and is open for writing.
+    if unsafe { libc::ftruncate(fd, len as libc::off_t) } == -1 {
+        let err = errno();
+        unsafe {
+            libc::close(fd);
+        }
+        set_errno(err);
+        return -1;
+    }
+
+    fd
+}
+
+/// Creates an anonymous POSIX shared memory object, returning an owned file descriptor.
+///
+/// This is identical to [`shm_open_anonymous`], except that on success the file descriptor is
+/// wrapped in an [`OwnedFd`](std::os::unix::io::OwnedFd), which closes it on drop, and on
+/// failure `errno` is captured into an [`io::Error`](std::io::Error) before any other libc call
+/// has a chance to clobber it.
+///
+/// The returned file descriptor can be used directly with [`std::fs::File::from`] or other APIs
+/// that accept an `OwnedFd`, such as `memmap2`.
+#[cfg(feature = "std")]
+pub fn shm_open_anonymous_fd() -> std::io::Result<std::os::unix::io::OwnedFd> {
+    use std::os::unix::io::FromRawFd;
+
+    let fd = shm_open_anonymous();
+    if fd == -1 {
+        Err(std::io::Error::last_os_error())
+    } else {
+        // Safety: fd is a valid, newly created file descriptor that we own.
+        Ok(unsafe { std::os::unix::io::OwnedFd::from_raw_fd(fd) })
+    }
 }
 
 #[cfg(test)]
@@ -149,7 +472,7 @@ mod test {
     #[cfg(not(any(target_os = "freebsd", target_os = "android")))]
     #[test]
     fn shm_open_anonymous_posix() {
-        let fd = super::shm_open_anonymous_posix();
+        let fd = super::shm_open_anonymous_posix(false);
         assert!(fd != -1);
         assert!(unsafe { libc::close(fd) } != -1);
     }
@@ -165,11 +488,98 @@ mod test {
             )
         };
         assert!(taken_fd != -1);
-        let fd = super::shm_open_anonymous_posix();
+        let fd = super::shm_open_anonymous_posix(false);
         unsafe {
             libc::close(taken_fd);
         }
         assert!(fd != -1);
         assert!(unsafe { libc::close(fd) } != -1);
     }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn shm_open_anonymous_fd() {
+        let fd = super::shm_open_anonymous_fd().unwrap();
+        drop(fd);
+    }
+
+    #[test]
+    fn shm_open_anonymous_with_cloexec() {
+        let fd = super::shm_open_anonymous_with(false);
+        assert!(fd != -1);
+        assert!(unsafe { libc::fcntl(fd, libc::F_GETFD) } & libc::FD_CLOEXEC != 0);
+        assert!(unsafe { libc::close(fd) } != -1);
+    }
+
+    #[test]
+    fn shm_open_anonymous_with_inheritable() {
+        let fd = super::shm_open_anonymous_with(true);
+        assert!(fd != -1);
+        assert!(unsafe { libc::fcntl(fd, libc::F_GETFD) } & libc::FD_CLOEXEC == 0);
+        assert!(unsafe { libc::close(fd) } != -1);
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[test]
+    fn seals() {
+        let fd = super::shm_open_anonymous_sealable();
+        assert!(fd != -1);
+
+        unsafe {
+            let seals = super::get_seals(fd).unwrap();
+            assert_eq!(seals, super::Seals::NONE);
+
+            let seals = super::add_seals(fd, super::Seals::SHRINK | super::Seals::GROW);
+            assert!(seals != -1);
+            assert!(super::get_seals(fd)
+                .unwrap()
+                .contains(super::Seals::SHRINK | super::Seals::GROW));
+
+            assert!(libc::close(fd) != -1);
+        }
+    }
+
+    #[cfg(target_os = "freebsd")]
+    #[test]
+    fn seals_unsupported() {
+        let fd = super::shm_open_anonymous_sealable();
+        assert_eq!(fd, -1);
+    }
+
+    #[test]
+    fn shm_open_anonymous_sized() {
+        let fd = super::shm_open_anonymous_sized(4096);
+        assert!(fd != -1);
+
+        let mut stat: libc::stat = unsafe { core::mem::zeroed() };
+        assert!(unsafe { libc::fstat(fd, &mut stat) } != -1);
+        assert_eq!(stat.st_size, 4096);
+
+        assert!(unsafe { libc::close(fd) } != -1);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn shm_open_anonymous_sized_with_huge_page() {
+        let fd = super::shm_open_anonymous_sized_with(
+            1 << 21,
+            super::SizedOptions::new().huge_page(super::HugePageSize::Mb2),
+        );
+        // Requires hugetlbfs pages to be reserved on the running system, so don't assert
+        // success; just make sure the call is well-formed and that a descriptor it does return
+        // can be closed.
+        if fd != -1 {
+            assert!(unsafe { libc::close(fd) } != -1);
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    #[test]
+    fn shm_open_anonymous_sized_with_huge_page_unsupported() {
+        let fd = super::shm_open_anonymous_sized_with(
+            1 << 21,
+            super::SizedOptions::new().huge_page(super::HugePageSize::Mb2),
+        );
+        assert_eq!(fd, -1);
+    }
 }